@@ -1,5 +1,6 @@
 #[macro_use]
 extern crate tracing;
+mod proxy;
 
 use std::{
     // net::{SocketAddr, TcpListener},
@@ -10,13 +11,21 @@ use std::{
 
 use anyhow::Result;
 use axum::{
-    extract::Host,
+    extract::{Host, MatchedPath, Path, Query, Request, State},
     handler::{self, HandlerWithoutStateExt},
     http::{uri::Scheme, StatusCode, Uri},
-    response::{IntoResponse, Redirect},
+    middleware::{self, Next},
+    response::{IntoResponse, Redirect, Response},
     routing::get,
-    BoxError, Router,
+    BoxError, Json, Router,
 };
+use axum_server::tls_rustls::RustlsAcceptor;
+use deadpool_postgres::{Config, ManagerConfig, Pool, RecyclingMethod, Runtime};
+use metrics::counter;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_postgres::NoTls;
 use axum_server::tls_rustls::RustlsConfig;
 use listenfd::ListenFd;
 use tokio::net::TcpListener;
@@ -39,10 +48,208 @@ const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/key.pem");
 
 const STATIC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/static");
 
+/// Connection string for the Postgres database holding the imported IPEDS
+/// data, read from `DATABASE_URL` with the same default the loader uses.
+fn database_url() -> String {
+    std::env::var("DATABASE_URL").unwrap_or_else(|_| "postgres://localhost/ipeds".to_owned())
+}
+
+/// Shared state threaded into every handler.
+#[derive(Clone)]
+struct AppState {
+    pool:    Pool,
+    metrics: PrometheusHandle,
+}
+
 async fn hello() -> &'static str {
     "Hello, World!"
 }
 
+/// Render this process's Prometheus metrics in the text exposition format.
+///
+/// These are the API server's own metrics (request counts via
+/// [`track_metrics`]). The ETL pipeline runs in the separate `ipeds` loader
+/// process and exposes its `cinder_ingest_*`/`cinder_convert_*` series on its
+/// own scrape listener (default `127.0.0.1:9000`); scrape both targets.
+async fn metrics(State(state): State<AppState>) -> String {
+    state.metrics.render()
+}
+
+/// Middleware that records one `cinder_api_requests_total` sample per request,
+/// labelled by method, route, and response status, so the server's `/metrics`
+/// reflects real traffic instead of an empty registry.
+async fn track_metrics(req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let path = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let response = next.run(req).await;
+    let status = response.status().as_u16().to_string();
+    counter!("cinder_api_requests_total",
+        "method" => method,
+        "path" => path,
+        "status" => status,
+    )
+    .increment(1);
+    response
+}
+
+/// Quote a Postgres identifier, doubling any embedded double quotes.
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Error type that renders `anyhow` failures as a 500 while letting handlers
+/// use `?`. A few variants map to more precise status codes.
+enum AppError {
+    NotFound(String),
+    BadRequest(String),
+    Internal(anyhow::Error),
+}
+
+impl<E: Into<anyhow::Error>> From<E> for AppError {
+    fn from(err: E) -> Self {
+        Self::Internal(err.into())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        match self {
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg).into_response(),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+            AppError::Internal(err) => {
+                error!(%err, "request failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
+            }
+        }
+    }
+}
+
+/// List the imported IPEDS tables in the `public` schema.
+async fn list_tables(State(state): State<AppState>) -> Result<Json<Vec<String>>, AppError> {
+    let conn = state.pool.get().await?;
+    let rows = conn
+        .query(
+            "SELECT table_name FROM information_schema.tables \
+             WHERE table_schema = 'public' AND table_type = 'BASE TABLE' \
+             ORDER BY table_name",
+            &[],
+        )
+        .await?;
+    Ok(Json(rows.iter().map(|r| r.get(0)).collect()))
+}
+
+#[derive(Debug, Deserialize)]
+struct TableQuery {
+    limit:   Option<i64>,
+    offset:  Option<i64>,
+    /// Comma-separated list of columns to project; defaults to every column.
+    columns: Option<String>,
+}
+
+/// Return a page of rows from `name` as JSON, with optional column projection.
+async fn get_table(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(q): Query<TableQuery>,
+) -> Result<Json<Vec<Value>>, AppError> {
+    let conn = state.pool.get().await?;
+
+    // Validate the table name against the catalog so it can be interpolated.
+    let known_cols: Vec<String> = conn
+        .query(
+            "SELECT column_name FROM information_schema.columns \
+             WHERE table_schema = 'public' AND table_name = $1",
+            &[&name],
+        )
+        .await?
+        .iter()
+        .map(|r| r.get(0))
+        .collect();
+    if known_cols.is_empty() {
+        return Err(AppError::NotFound(format!("unknown table '{name}'")));
+    }
+
+    // Project only requested columns, rejecting anything not in the catalog.
+    let projection = match &q.columns {
+        Some(cols) => {
+            let picked: Vec<&str> = cols.split(',').map(str::trim).filter(|c| !c.is_empty()).collect();
+            for col in &picked {
+                if !known_cols.iter().any(|k| k == col) {
+                    return Err(AppError::BadRequest(format!("unknown column '{col}'")));
+                }
+            }
+            picked.iter().map(|c| quote_ident(c)).collect::<Vec<_>>().join(", ")
+        }
+        None => "*".to_owned(),
+    };
+
+    let limit = q.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = q.offset.unwrap_or(0).max(0);
+    let sql = format!(
+        "SELECT to_jsonb(t) AS j FROM (SELECT {projection} FROM {} LIMIT $1 OFFSET $2) t",
+        quote_ident(&name)
+    );
+    let rows = conn.query(&sql, &[&limit, &offset]).await?;
+    Ok(Json(rows.iter().map(|r| r.get::<_, Value>("j")).collect()))
+}
+
+/// Look up a single institution by `UNITID`, joining the core IPEDS directory
+/// tables for the most recent year: the directory (`HD<year>`) and, when it has
+/// been imported, institutional characteristics (`IC<year>`) on `UNITID`. The
+/// `HD` match is anchored to `hd` followed by a four-digit year so unrelated
+/// tables that merely start with `hd` are never selected.
+async fn get_institution(
+    State(state): State<AppState>,
+    Path(unitid): Path<i32>,
+) -> Result<Json<Value>, AppError> {
+    let conn = state.pool.get().await?;
+    let directory: String = conn
+        .query_opt(
+            "SELECT table_name FROM information_schema.tables \
+             WHERE table_schema = 'public' AND table_name::text ~ '^hd[0-9]{4}$' \
+             ORDER BY table_name DESC LIMIT 1",
+            &[],
+        )
+        .await?
+        .ok_or_else(|| AppError::NotFound("no directory (HD) table imported".to_owned()))?
+        .get(0);
+
+    // Join the institutional-characteristics table for the same year when it is
+    // present, merging its columns over the directory record.
+    let ic = format!("ic{}", &directory[2..]);
+    let has_ic: bool = conn
+        .query_one(
+            "SELECT EXISTS(SELECT 1 FROM information_schema.tables \
+             WHERE table_schema = 'public' AND table_name = $1)",
+            &[&ic],
+        )
+        .await?
+        .get(0);
+
+    let sql = if has_ic {
+        format!(
+            "SELECT to_jsonb(hd) || coalesce(to_jsonb(ic), '{{}}'::jsonb) AS j \
+             FROM {} hd LEFT JOIN {} ic USING (unitid) WHERE hd.unitid = $1",
+            quote_ident(&directory),
+            quote_ident(&ic)
+        )
+    } else {
+        format!(
+            "SELECT to_jsonb(t) AS j FROM {} t WHERE unitid = $1",
+            quote_ident(&directory)
+        )
+    };
+    let row = conn
+        .query_opt(&sql, &[&unitid])
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("no institution with unitid {unitid}")))?;
+    Ok(Json(row.get::<_, Value>("j")))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
@@ -50,6 +257,19 @@ async fn main() -> Result<()> {
     let tls_config = RustlsConfig::from_pem_file(CERT, KEY).await?;
     debug!(?tls_config, "loaded TLS configuration");
 
+    let mut cfg = Config::new();
+    cfg.url = Some(database_url());
+    cfg.manager = Some(ManagerConfig {
+        recycling_method: RecyclingMethod::Fast,
+    });
+    let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls)?;
+    let metrics_handle = PrometheusBuilder::new().install_recorder()?;
+    let state = AppState {
+        pool,
+        metrics: metrics_handle,
+    };
+    debug!("created Postgres connection pool");
+
     let compression = CompressionLayer::new()
         .br(true)
         .deflate(true)
@@ -63,9 +283,15 @@ async fn main() -> Result<()> {
     let app = Router::new()
         .nest_service("/static", static_)
         .route("/", get(hello))
+        .route("/api/tables", get(list_tables))
+        .route("/api/tables/:name", get(get_table))
+        .route("/api/institutions/:unitid", get(get_institution))
+        .route("/metrics", get(metrics))
+        .route_layer(middleware::from_fn(track_metrics))
         .layer(TraceLayer::new_for_http())
         .layer(compression)
-        .fallback(handler_404);
+        .fallback(handler_404)
+        .with_state(state);
 
     let mut listenfd = ListenFd::from_env();
     let listener = match listenfd.take_tcp_listener(0)? {
@@ -82,7 +308,18 @@ async fn main() -> Result<()> {
     };
     tokio::spawn(http_to_https(PORTS));
 
-    axum_server::from_tcp_rustls(listener, tls_config)
+    // Optionally peel a PROXY protocol header off each connection so the true
+    // client address survives an upstream L4 balancer or tunnel.
+    let proxy_enabled = std::env::var("PROXY_PROTOCOL")
+        .map(|v| !v.is_empty() && v != "0")
+        .unwrap_or(false);
+    if proxy_enabled {
+        debug!("PROXY protocol header parsing enabled");
+    }
+    let acceptor = proxy::ProxyProtocolAcceptor::new(RustlsAcceptor::new(tls_config), proxy_enabled);
+
+    axum_server::from_tcp(listener)
+        .acceptor(acceptor)
         .serve(app.into_make_service())
         .await?;
     Ok(())
@@ -0,0 +1,186 @@
+//! Optional PROXY protocol (v1/v2) support for the TLS listener.
+//!
+//! When the server sits behind an L4 load balancer or a tunnel, every
+//! connection appears to originate from `127.0.0.1`. If enabled, this module
+//! consumes a PROXY protocol header from the front of each accepted connection
+//! before the rustls handshake, recovers the real client `SocketAddr`, and
+//! injects it into request extensions as [`ClientAddr`] so handlers and the
+//! `TraceLayer` can log the true client IP.
+
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum_server::accept::Accept;
+use bytes::{Buf, BytesMut};
+use proxy_protocol::{parse, ProxyHeader};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    time::timeout,
+};
+use tower_http::add_extension::AddExtension;
+
+/// How long to wait for a PROXY protocol header before assuming the connection
+/// is a direct client (or a slow TLS `ClientHello`) and handing it to TLS. A
+/// connection that sends no header would otherwise block forever, because the
+/// peer is waiting for the TLS `ServerHello` and will send nothing more.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The client's source address, as recovered from a PROXY protocol header.
+/// `None` when the header was absent or carried an unsupported address family.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub Option<SocketAddr>);
+
+/// Read and consume a PROXY protocol header from `io`, returning the parsed
+/// source address and any bytes that were read past the header (which belong
+/// to the TLS stream and must be replayed).
+async fn read_proxy_header<I>(io: &mut I) -> io::Result<(Option<SocketAddr>, BytesMut)>
+where
+    I: AsyncRead + Unpin,
+{
+    // The v2 header is at most 536 bytes; the v1 line is much smaller. In
+    // practice the whole header arrives in the first segment, but keep reading
+    // while `parse` fails and fewer than 536 bytes are buffered.
+    let mut buf = BytesMut::with_capacity(536);
+    let mut tmp = [0u8; 536];
+    loop {
+        let n = match timeout(PROXY_HEADER_TIMEOUT, io.read(&mut tmp)).await {
+            Ok(res) => res?,
+            // Nothing (more) arrived in time: this isn't a PROXY-prefixed
+            // connection, so replay whatever we have and let TLS proceed rather
+            // than blocking on a header that will never come.
+            Err(_elapsed) => return Ok((None, buf)),
+        };
+        if n == 0 {
+            return Ok((None, buf));
+        }
+        buf.extend_from_slice(&tmp[..n]);
+
+        let mut cursor = io::Cursor::new(&buf[..]);
+        match parse(&mut cursor) {
+            Ok(header) => {
+                let consumed = cursor.position() as usize;
+                buf.advance(consumed);
+                return Ok((source_addr(&header), buf));
+            }
+            // Fewer than 536 bytes buffered: the error may only mean the header
+            // is not fully buffered yet, so read more. Parse errors are not told
+            // apart here; a connection without a header leaves this loop on EOF,
+            // on the timeout above, or once 536 bytes are buffered.
+            Err(_) if buf.len() < tmp.len() => continue,
+            // 536 bytes buffered and still no header; treat everything buffered
+            // as stream payload and let TLS deal with it.
+            Err(_) => return Ok((None, buf)),
+        }
+    }
+}
+
+fn source_addr(header: &ProxyHeader) -> Option<SocketAddr> {
+    use proxy_protocol::{version1::ProxyAddresses as V1, version2::ProxyAddresses as V2};
+    match header {
+        ProxyHeader::Version1 { addresses } => match addresses {
+            V1::Ipv4 { source, .. } => Some(SocketAddr::V4(*source)),
+            V1::Ipv6 { source, .. } => Some(SocketAddr::V6(*source)),
+            _ => None,
+        },
+        ProxyHeader::Version2 { addresses, .. } => match addresses {
+            V2::Ipv4 { source, .. } => Some(SocketAddr::V4(*source)),
+            V2::Ipv6 { source, .. } => Some(SocketAddr::V6(*source)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// An IO stream with a buffer of already-read bytes replayed ahead of the
+/// underlying stream, so reads that consumed past the PROXY header can be
+/// handed back to the TLS acceptor transparently.
+pub struct PrefixedStream<I> {
+    inner:  I,
+    prefix: BytesMut,
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for PrefixedStream<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.prefix.is_empty() {
+            let n = std::cmp::min(this.prefix.len(), buf.remaining());
+            buf.put_slice(&this.prefix[..n]);
+            this.prefix.advance(n);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// An [`Accept`] that optionally strips a PROXY protocol header before handing
+/// the stream to `inner` (typically the rustls acceptor).
+#[derive(Clone)]
+pub struct ProxyProtocolAcceptor<A> {
+    inner:   A,
+    enabled: bool,
+}
+
+impl<A> ProxyProtocolAcceptor<A> {
+    pub fn new(inner: A, enabled: bool) -> Self {
+        Self { inner, enabled }
+    }
+}
+
+impl<A, I, S> Accept<I, S> for ProxyProtocolAcceptor<A>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+    A: Accept<PrefixedStream<I>, AddExtension<S, ClientAddr>> + Clone + Send + 'static,
+    A::Future: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future =
+        Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, mut stream: I, service: S) -> Self::Future {
+        let inner = self.inner.clone();
+        let enabled = self.enabled;
+        Box::pin(async move {
+            let (addr, prefix) = if enabled {
+                read_proxy_header(&mut stream).await?
+            } else {
+                (None, BytesMut::new())
+            };
+            let prefixed = PrefixedStream {
+                inner: stream,
+                prefix,
+            };
+            let service = AddExtension::new(service, ClientAddr(addr));
+            inner.accept(prefixed, service).await
+        })
+    }
+}
@@ -1,36 +1,46 @@
 #![feature(let_chains, generic_arg_infer, exit_status_error)]
 #[macro_use]
 extern crate tracing;
+mod manifest;
+mod store;
+
 use std::{
-    io::{Error, ErrorKind},
+    io::{IsTerminal, Write},
     path::{Path, PathBuf},
-    sync::Arc,
+    process::Stdio,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::{anyhow, bail, Result};
 use async_zip::base::read::stream::ZipFileReader;
+use bytes::Bytes;
 use clap::Parser;
 use deadpool_postgres::{Config, ManagerConfig, Pool, PoolConfig, RecyclingMethod, Runtime};
 use futures::{
-    stream::{self, TryStreamExt},
-    StreamExt,
+    stream, SinkExt, StreamExt,
 };
 use human_bytes::human_bytes;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use lazy_static::lazy_static;
+use metrics::{counter, gauge, histogram};
 use reqwest::{Client, ClientBuilder, Url};
 use scraper::{Html, Selector};
 use tempfile::{tempdir, TempDir};
 use tokio::{
     fs::{create_dir_all, OpenOptions},
-    io,
-    io::{AsyncWriteExt, BufReader as TokioBufReader, BufWriter as TokioBufWriter},
+    io::{AsyncReadExt, AsyncWriteExt, BufReader as TokioBufReader, BufWriter as TokioBufWriter},
     process::Command,
 };
 use tokio_postgres::NoTls;
-use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio_util::{
+    compat::{FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt},
+    io::ReaderStream,
+};
 use tracing_subscriber::EnvFilter;
 use which::which;
 
+use crate::{manifest::Manifest, store::Store};
+
 lazy_static! {
     static ref NCES: Url = Url::parse("https://nces.ed.gov").unwrap();
     static ref IPEDS: Url = NCES
@@ -46,6 +56,47 @@ lazy_static! {
         which("mdb-schema").expect("'mdb-schema' command not in $PATH");
 }
 
+/// A `tracing` writer that prints through a [`MultiProgress`] so log lines are
+/// interleaved with the progress bars instead of corrupting them.
+#[derive(Clone)]
+struct ProgressWriter(Arc<MultiProgress>);
+
+struct ProgressWriterGuard(Arc<MultiProgress>);
+
+impl Write for ProgressWriterGuard {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Suspend the bars while emitting the line so they redraw intact.
+        self.0.suspend(|| std::io::stderr().write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for ProgressWriter {
+    type Writer = ProgressWriterGuard;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        ProgressWriterGuard(self.0.clone())
+    }
+}
+
+/// Style for a per-archive download bar driven by `Content-Length`.
+fn download_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg:>24} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec})",
+    )
+    .unwrap()
+    .progress_chars("=> ")
+}
+
+/// Style for a per-mdb conversion bar counting completed table COPYs.
+fn convert_style() -> ProgressStyle {
+    ProgressStyle::with_template("{msg:>24} {spinner} {pos}/{len} tables").unwrap()
+}
+
 #[derive(Debug, Parser)]
 struct Args {
     #[clap(short, long, default_value = "postgres://localhost/ipeds")]
@@ -58,6 +109,10 @@ struct Args {
     #[clap(short, long, default_value = "out/")]
     /// Directory to store the raw IPEDS data
     out:           PathBuf,
+    #[clap(long, default_value = "file://out/")]
+    /// Backend for the raw IPEDS data: a `file://<dir>` path or an
+    /// `s3://<bucket>/<prefix>` URI
+    store:         String,
     #[clap(long)]
     /// Drop all existing IPEDS tables in the database before inserting the new
     /// ones
@@ -65,15 +120,45 @@ struct Args {
     #[clap(long)]
     /// Vacuum and analyze the database after inserting the IPEDS data
     optimize:      bool,
+    #[clap(long)]
+    /// Re-download and re-import every archive even if the manifest reports it
+    /// is unchanged
+    force:         bool,
+    #[clap(long, value_enum, default_value_t = Mode::Download)]
+    /// Pipeline mode: `download` fetches and imports (consulting the manifest,
+    /// or use `--force` to re-import everything), `convert-only` imports the
+    /// Access databases already present in `out/` with no network access, and
+    /// `refresh` re-downloads changed archives (honouring the manifest) and
+    /// then re-converts everything already on disk
+    mode:          Mode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Mode {
+    Download,
+    ConvertOnly,
+    Refresh,
 }
 
+/// Quote a Postgres identifier, doubling any embedded double quotes so table
+/// names containing quotes, spaces, or other special characters can be used
+/// safely in a `COPY` statement.
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn get_mdb_convert(
     url: Url,
-    docs_out: Arc<PathBuf>,
+    store: Arc<dyn Store>,
     tmp: Arc<TempDir>,
+    out: Arc<PathBuf>,
     db: Pool,
     drop_existing: bool,
     client: Client,
+    manifest: Arc<Mutex<Manifest>>,
+    force: bool,
+    mp: Arc<MultiProgress>,
 ) -> Result<()> {
     // TODO: Make the path conversion/sanatization less cluttered
     let name = url
@@ -81,16 +166,100 @@ async fn get_mdb_convert(
         .ok_or(anyhow!("'{}': no path segments found", &url))?
         .last()
         .ok_or(anyhow!("'{}': no last path segment found", &url))?;
-    info!("UNZIP: START '{}' -> '{}'", name, docs_out.display());
-    let resp = client.get(url.clone()).send().await?.error_for_status()?;
+    info!("UNZIP: START '{}' -> store", name);
+    gauge!("cinder_ingest_inflight_tasks").increment(1.0);
+
+    // Consult the manifest and, unless forced, issue a conditional GET so the
+    // server can tell us the archive is unchanged with a cheap 304.
+    let cached = manifest.lock().unwrap().get(url.as_str()).cloned();
+    let mut req = client.get(url.clone());
+    if !force {
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                req = req.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(lm) = &entry.last_modified {
+                req = req.header(reqwest::header::IF_MODIFIED_SINCE, lm);
+            }
+        }
+    }
+    let resp = req.send().await?;
+    if !force && resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        info!("CACHE: HIT(304) '{}' -> skipping", name);
+        counter!("cinder_ingest_cache_hits_total").increment(1);
+        gauge!("cinder_ingest_inflight_tasks").decrement(1.0);
+        return Ok(());
+    }
+    let resp = resp.error_for_status()?;
+
+    // Capture the validators for the new manifest entry before the body is
+    // consumed.
+    let header = |name: reqwest::header::HeaderName| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+    };
+    let etag = header(reqwest::header::ETAG);
+    let last_modified = header(reqwest::header::LAST_MODIFIED);
+
+    // One download bar per archive, sized from Content-Length when advertised.
+    let bar = mp.add(ProgressBar::new(resp.content_length().unwrap_or(0)));
+    bar.set_style(download_style());
+    bar.set_message(name.to_owned());
 
-    let stream = resp
-        .bytes_stream()
-        .map_err(|e| Error::new(ErrorKind::Other, e))
-        .into_async_read();
+    // Stream the body to a scratch file, hashing as we go, so the archive's
+    // BLAKE3 fingerprint is known *before* any unzip/convert work. IPEDS
+    // servers frequently answer `200` even for unchanged archives, so a content
+    // hash is the only reliable cache key when conditional GETs are ignored.
+    let archive_path = tmp.path().join(name);
+    let mut hasher = blake3::Hasher::new();
+    let mut len = 0u64;
+    {
+        let f = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&archive_path)
+            .await?;
+        let mut wrtr = TokioBufWriter::new(f);
+        let mut body = resp.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            len += chunk.len() as u64;
+            bar.inc(chunk.len() as u64);
+            wrtr.write_all(&chunk).await?;
+        }
+        wrtr.flush().await?;
+    }
+    bar.finish_and_clear();
+    let hash = hasher.finalize().to_hex().to_string();
+    counter!("cinder_ingest_bytes_downloaded_total", "archive" => name.to_owned()).increment(len);
+
+    // Content-addressed cache hit: the bytes are byte-for-byte what we already
+    // imported, so skip the unzip+convert entirely and just refresh the
+    // validators for the next run's conditional GET.
+    if !force && cached.as_ref().is_some_and(|e| e.blake3 == hash) {
+        info!("CACHE: HIT(hash) '{}' -> skipping unzip+convert", name);
+        counter!("cinder_ingest_cache_hits_total").increment(1);
+        gauge!("cinder_ingest_inflight_tasks").decrement(1.0);
+        manifest.lock().unwrap().set(
+            url.as_str().to_owned(),
+            manifest::Entry {
+                blake3: hash,
+                len,
+                etag,
+                last_modified,
+            },
+        );
+        return Ok(());
+    }
 
+    // New or changed content: unzip the scratch archive and import it.
+    let archive = tokio::fs::File::open(&archive_path).await?;
     let mut convert_handle = None;
-    let mut dcdr = ZipFileReader::new(stream);
+    let mut dcdr = ZipFileReader::new(TokioBufReader::new(archive).compat());
     loop {
         let reading = dcdr.next_with_entry().await?;
         if let Some(mut reading) = reading {
@@ -106,46 +275,55 @@ async fn get_mdb_convert(
 
                 let is_mdb = ext == "accdb";
 
-                let filepath = if !is_mdb {
-                    let type_docs_out = docs_out.join(ext);
-                    if !type_docs_out.exists() {
-                        warn!("CREATE: '{}'", type_docs_out.display());
-                        create_dir_all(&type_docs_out).await?;
-                    }
-                    type_docs_out.join(filename)
-                } else {
-                    tmp.path().join(filename)
-                };
-
-                let f = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(&filepath)
-                    .await?;
-                let mut wrtr = TokioBufWriter::new(f);
-                let mut rdr = TokioBufReader::new(rdr.compat());
-                let bytes = io::copy(&mut rdr, &mut wrtr).await?;
-
-                info!(
-                    "WRITE: '{}'({})",
-                    filepath.display(),
-                    human_bytes(bytes as f64),
-                );
-
                 if is_mdb {
+                    // The Access database needs to land on a real local path so
+                    // mdb-tools can open it, and it is persisted under `out/`
+                    // (not the ephemeral tempdir) so a later `convert-only` run
+                    // can re-import it without re-fetching. Everything else is
+                    // routed through the configured store.
+                    let filepath = out.join(filename);
+                    let f = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&filepath)
+                        .await?;
+                    let mut wrtr = TokioBufWriter::new(f);
+                    let mut rdr = TokioBufReader::new(rdr.compat());
+                    let bytes = tokio::io::copy(&mut rdr, &mut wrtr).await?;
+                    wrtr.flush().await?;
+                    info!("WRITE: '{}'({})", filepath.display(), human_bytes(bytes as f64));
+
                     if convert_handle.is_none() {
                         // Immediately begin converting the MDB file to SQL while still decoding the
                         // rest of the archrive
                         convert_handle = Some(tokio::spawn(convert_mdb(
                             filepath,
-                            docs_out.clone(),
+                            store.clone(),
                             db.clone(),
                             drop_existing,
+                            mp.clone(),
                         )));
                     } else {
                         bail!("'{}': multiple MDB files found", url.as_ref());
                     }
+                } else {
+                    // Buffer the documentation entry and hand it to the store
+                    // keyed by its extension folder (e.g. `pdf/<file>`).
+                    let mut buf = Vec::new();
+                    TokioBufReader::new(rdr.compat())
+                        .read_to_end(&mut buf)
+                        .await?;
+                    let bytes = buf.len();
+                    let rel = Path::new(ext).join(filename);
+                    let key = store::key(&rel);
+                    store
+                        .put(
+                            &key,
+                            stream::once(async move { Ok(Bytes::from(buf)) }).boxed(),
+                        )
+                        .await?;
+                    info!("WRITE: '{}'({})", key, human_bytes(bytes as f64));
                 }
             }
             dcdr = reading.skip().await?;
@@ -153,18 +331,31 @@ async fn get_mdb_convert(
             break;
         }
     }
-    info!("UNZIP: FINISH '{}' -> '{}'", name, docs_out.display());
+    info!("UNZIP: FINISH '{}' -> store", name);
     if let Some(handle) = convert_handle {
         handle.await??;
     }
+
+    // Record the fresh fingerprint so the next run can short-circuit.
+    gauge!("cinder_ingest_inflight_tasks").decrement(1.0);
+    manifest.lock().unwrap().set(
+        url.as_str().to_owned(),
+        manifest::Entry {
+            blake3: hash,
+            len,
+            etag,
+            last_modified,
+        },
+    );
     Ok(())
 }
 
 async fn convert_mdb(
     mdb_in: PathBuf,
-    docs_out: Arc<PathBuf>,
+    store: Arc<dyn Store>,
     db: Pool,
     drop_existing: bool,
+    mp: Arc<MultiProgress>,
 ) -> Result<()> {
     let mdbname = mdb_in
         .file_stem()
@@ -173,11 +364,7 @@ async fn convert_mdb(
         .ok_or(anyhow!("'{}': invalid path", mdb_in.display()))?
         .to_owned();
     info!("CONVERT: START '{}' -> SQL", mdbname);
-    let schema_dir = docs_out.join("schema/");
-    if !schema_dir.exists() {
-        warn!("CREATE: '{}'", schema_dir.display());
-        create_dir_all(schema_dir.as_path()).await?;
-    }
+    let started = std::time::Instant::now();
     // Get schema with mdb-schema
     let mut cmd = Command::new(MDB_SCHEMA.as_path());
     cmd.arg("--no-relations");
@@ -189,20 +376,18 @@ async fn convert_mdb(
     let raw_schema = cmd.output().await?.stdout;
     let schema_sql = std::str::from_utf8(&raw_schema)?;
 
-    let schema_path = schema_dir.join(&mdbname).with_extension("sql");
-    let f = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&schema_path)
+    let schema_key = format!("schema/{}.sql", mdbname);
+    let schema_bytes = Bytes::from(raw_schema.clone());
+    store
+        .put(
+            &schema_key,
+            stream::once(async move { Ok(schema_bytes) }).boxed(),
+        )
         .await?;
-    let mut wrtr = TokioBufWriter::new(f);
-    wrtr.write_all(schema_sql.as_bytes()).await?;
-    wrtr.flush().await?;
 
     info!(
         "WRITE: '{}'({})",
-        schema_path.display(),
+        schema_key,
         human_bytes(schema_sql.len() as f64)
     );
 
@@ -221,6 +406,9 @@ async fn convert_mdb(
     // Spawn tasks for exporting the tables with mdb-export
     let mdb_in = Arc::new(mdb_in);
     let mdbname = Arc::new(mdbname);
+    let bar = mp.add(ProgressBar::new(tables.lines().count() as u64));
+    bar.set_style(convert_style());
+    bar.set_message((*mdbname).clone());
     let export_handles = tables
         .lines()
         .map(|table| {
@@ -229,36 +417,130 @@ async fn convert_mdb(
             let mdbname = mdbname.clone();
             let table = table.to_owned();
             tokio::spawn(async move {
-                let cmd = format!(
-                    "{} -H {} {}", // -H: no header row
-                    MDB_EXPORT.canonicalize()?.display(),
-                    mdb_in.display(),
-                    // .ok_or(anyhow!("'{}': invalid path", mdb_in.display()))?,
+                // Stream `mdb-export` straight into a server-side COPY FROM STDIN.
+                // This avoids the superuser-only COPY FROM PROGRAM path and never
+                // interpolates the table name into a shell command.
+                let mut child = Command::new(MDB_EXPORT.as_path())
+                    .arg("-H") // -H: no header row
+                    .arg(mdb_in.as_path())
+                    .arg(&table)
+                    .stdout(Stdio::piped())
+                    .kill_on_drop(true) // reap the child if the COPY errors mid-stream
+                    .spawn()?;
+                let stdout = child.stdout.take().ok_or(anyhow!(
+                    "'{}.{}': failed to capture mdb-export stdout",
+                    mdbname,
                     table
-                );
-                let sql = format!("COPY {} FROM PROGRAM '{}' (FORMAT csv);", table, cmd);
+                ))?;
+
+                let sql = format!("COPY {} FROM STDIN (FORMAT csv)", quote_ident(&table));
                 let conn = db.get().await?;
                 trace!("EXEC: '{}'", sql);
-                conn.batch_execute(&sql).await?;
+                let sink = conn.copy_in(&sql).await?;
+                futures::pin_mut!(sink);
+                let mut stream = ReaderStream::new(stdout);
+                while let Some(chunk) = stream.next().await {
+                    sink.send(chunk?).await?;
+                }
+                // `COPY` reports the exact number of rows ingested, which stays
+                // accurate even when a quoted field contains an embedded newline.
+                let rows = sink.finish().await?;
+                child.wait().await?.exit_ok()?;
                 drop(conn);
-                debug!("COPY: {}.{} -> SQL", mdbname, table);
-                anyhow::Ok(())
+                counter!("cinder_convert_rows_total", "mdb" => (*mdbname).clone()).increment(rows);
+                debug!("COPY: {}.{}({} rows) -> SQL", mdbname, table, rows);
+                anyhow::Ok(rows)
             })
         })
         .collect::<Vec<_>>();
+    let mut tables = 0u64;
     for handle in export_handles {
         handle.await??;
+        tables += 1;
+        bar.inc(1);
     }
-    info!("CONVERT: FINISHED '{}' -> SQL", mdbname);
+    bar.finish_and_clear();
+    counter!("cinder_convert_tables_total", "mdb" => (*mdbname).clone()).increment(tables);
+    histogram!("cinder_convert_duration_seconds", "mdb" => (*mdbname).clone())
+        .record(started.elapsed().as_secs_f64());
+    info!("CONVERT: FINISHED '{}'({} tables) -> SQL", mdbname, tables);
     Ok(())
 }
 
+/// Offline conversion: walk an already-populated directory for `.accdb` Access
+/// databases and run the same schema+table pipeline against `db` without any
+/// network access.
+async fn convert_existing(
+    out: PathBuf,
+    store: Arc<dyn Store>,
+    db: Pool,
+    drop_existing: bool,
+    concurrency: usize,
+    mp: Arc<MultiProgress>,
+) -> Result<()> {
+    let mdbs = find_accdb(&out).await?;
+    if mdbs.is_empty() {
+        warn!("CONVERT: no '.accdb' files found under '{}'", out.display());
+        return Ok(());
+    }
+    info!("CONVERT: found {} '.accdb' file(s) under '{}'", mdbs.len(), out.display());
+    let mut results = stream::iter(mdbs.into_iter().map(|mdb| {
+        let store = store.clone();
+        let db = db.clone();
+        let mp = mp.clone();
+        tokio::spawn(convert_mdb(mdb, store, db, drop_existing, mp))
+    }))
+    .buffer_unordered(concurrency);
+    while let Some(res) = results.next().await {
+        res??;
+    }
+    Ok(())
+}
+
+/// Recursively collect every `.accdb` file beneath `root`.
+async fn find_accdb(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let mut rd = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = rd.next_entry().await? {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "accdb") {
+                found.push(path);
+            }
+        }
+    }
+    Ok(found)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Progress bars share stderr with the log stream, so route `tracing`
+    // through the `MultiProgress` and disable the bars when stderr is not a
+    // TTY (piped/CI output).
+    let mp = Arc::new(MultiProgress::new());
+    if !std::io::stderr().is_terminal() {
+        mp.set_draw_target(ProgressDrawTarget::hidden());
+    }
     tracing_subscriber::fmt::fmt()
         .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(ProgressWriter(mp.clone()))
         .init();
     let mut args = Args::parse();
+
+    // The loader is a separate, short-lived process from the axum API, so its
+    // ETL counters/histograms (`cinder_ingest_*`, `cinder_convert_*`) cannot be
+    // served by the API's `/metrics`. Expose them on this process's own scrape
+    // listener (default 127.0.0.1:9000) instead and log the address so it can
+    // be wired into Prometheus alongside the API target.
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .with_http_listener(([127, 0, 0, 1], 9000))
+        .install()?;
+    info!("METRICS: ETL metrics exposed at http://127.0.0.1:9000/metrics");
+    gauge!("cinder_ingest_concurrency").set(args.concurrency as f64);
+
     let client = ClientBuilder::new()
         // .http3_prior_knowledge() Reqwest support for HTTP3 p
         .brotli(true)
@@ -267,42 +549,84 @@ async fn main() -> Result<()> {
         .https_only(true)
         .build()?;
 
-    if !args.out.exists() {
-        // args.out = args.out.canonicalize()?;
-        warn!("CREATE: '{}'", args.out.display());
-        create_dir_all(args.out.as_path()).await?;
-        args.out = args.out.canonicalize()?;
+    let poolcfg = PoolConfig::default(); // We don't use args.concurrency here because
+    let mut cfg = Config::new();
+    cfg.pool = Some(poolcfg);
+    cfg.url = Some(args.pg.clone());
+    cfg.manager = Some(ManagerConfig {
+        recycling_method: RecyclingMethod::Fast,
+    });
+    let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls)?;
 
-        let poolcfg = PoolConfig::default(); // We don't use args.concurrency here because
-        let mut cfg = Config::new();
-        cfg.pool = Some(poolcfg);
-        cfg.url = Some(args.pg.clone());
-        cfg.manager = Some(ManagerConfig {
-            recycling_method: RecyclingMethod::Fast,
-        });
-        let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls)?;
-
-        if args.drop_existing {
-            error!(
-                "!!! THIS WILL DROP EXISTING IPEDS TABLES IN '{}' !!!",
-                args.pg
-            );
+    if args.drop_existing {
+        error!(
+            "!!! THIS WILL DROP EXISTING IPEDS TABLES IN '{}' !!!",
+            args.pg
+        );
+    }
+
+    if args.mode == Mode::ConvertOnly {
+        // Offline: import whatever is already on disk, no network access.
+        if !args.out.exists() {
+            bail!("'{}': does not exist, nothing to convert", args.out.display());
+        }
+        args.out = args.out.canonicalize()?;
+        let store: Arc<dyn Store> = store::from_uri(&args.store)?.into();
+        warn!("CONVERT-ONLY: importing existing data from '{}'", args.out.display());
+        convert_existing(
+            args.out,
+            store,
+            pool.clone(),
+            args.drop_existing,
+            args.concurrency,
+            mp.clone(),
+        )
+        .await?;
+        if args.optimize {
+            let conn = pool.get().await?;
+            warn!("EXEC: 'VACUUM(FULL, ANALYZE);' - THIS MAY TAKE A WHILE!");
+            conn.batch_execute("VACUUM(FULL, ANALYZE);").await?;
+        }
+    } else {
+        // Honour the manifest unless `--force` asks for a full re-import.
+        let force = args.force;
+        if !args.out.exists() {
+            warn!("CREATE: '{}'", args.out.display());
+            create_dir_all(args.out.as_path()).await?;
         }
+        args.out = args.out.canonicalize()?;
 
         let raw_html = client.get(IPEDS.clone()).send().await?.text().await?;
         let html = Html::parse_document(&raw_html);
 
         let tmp = Arc::new(tempdir()?); // Closes temp dir when dropped!
-        let out = Arc::new(args.out);
+        let out = Arc::new(args.out.clone());
+        let store: Arc<dyn Store> = store::from_uri(&args.store)?.into();
+        let manifest = Arc::new(Mutex::new(Manifest::load(store.as_ref()).await?));
         let mut results = stream::iter(html.select(&SEL_MDB_LINK).map(|el| {
             let href = el.value().attr("href").unwrap().to_owned();
             let pool = pool.clone();
             let client = client.clone();
-            let out = out.clone();
+            let store = store.clone();
             let tmp = tmp.clone();
+            let out = out.clone();
+            let manifest = manifest.clone();
+            let mp = mp.clone();
             tokio::spawn(async move {
                 let url = IPEDS.join(&href)?;
-                get_mdb_convert(url, out, tmp, pool, args.drop_existing, client).await
+                get_mdb_convert(
+                    url,
+                    store,
+                    tmp,
+                    out,
+                    pool,
+                    args.drop_existing,
+                    client,
+                    manifest,
+                    force,
+                    mp,
+                )
+                .await
             })
         }))
         .buffer_unordered(args.concurrency);
@@ -310,17 +634,33 @@ async fn main() -> Result<()> {
         while let Some(res) = results.next().await {
             res??;
         }
+        // Every task has joined, so the manifest is no longer shared.
+        let manifest = Arc::try_unwrap(manifest)
+            .map_err(|_| anyhow!("manifest still referenced at shutdown"))?
+            .into_inner()
+            .unwrap();
+        manifest.save(store.as_ref()).await?;
+
+        // `refresh` re-converts everything on disk after the download pass so
+        // unchanged archives (skipped by the manifest, never re-fetched) are
+        // still re-imported.
+        if args.mode == Mode::Refresh {
+            warn!("REFRESH: re-converting existing data from '{}'", args.out.display());
+            convert_existing(
+                args.out,
+                store.clone(),
+                pool.clone(),
+                args.drop_existing,
+                args.concurrency,
+                mp.clone(),
+            )
+            .await?;
+        }
         if args.optimize {
             let conn = pool.get().await?;
             warn!("EXEC: 'VACUUM(FULL, ANALYZE);' - THIS MAY TAKE A WHILE!");
             conn.batch_execute("VACUUM(FULL, ANALYZE);").await?;
         }
-    } else {
-        warn!(
-            "{} already exists! continuing to conversion",
-            args.out.display()
-        );
-        unimplemented!("a separate way to convert the mdb files");
     }
     Ok(())
 }
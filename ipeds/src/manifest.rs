@@ -0,0 +1,74 @@
+//! Content-addressed ingest manifest.
+//!
+//! IPEDS re-publishes the same yearly archives across runs. The manifest
+//! records, per source URL, the BLAKE3 hash and byte length of the archive we
+//! last downloaded along with the HTTP validators (`ETag`/`Last-Modified`) the
+//! server returned. On the next run those validators drive a conditional GET so
+//! unchanged archives are skipped without re-fetching hundreds of megabytes.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::store::Store;
+
+/// Key the manifest is persisted under in the configured [`Store`].
+const MANIFEST_KEY: &str = "manifest.json";
+
+/// One archive's cached fingerprint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// BLAKE3 hash of the downloaded archive bytes, hex encoded.
+    pub blake3:        String,
+    /// Length of the downloaded archive in bytes.
+    pub len:           u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag:          Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// The full manifest, keyed by source URL.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    entries: HashMap<String, Entry>,
+}
+
+impl Manifest {
+    /// Load the manifest from `store`, returning an empty manifest when none
+    /// has been written yet.
+    pub async fn load(store: &dyn Store) -> Result<Self> {
+        if !store.exists(MANIFEST_KEY).await? {
+            return Ok(Self::default());
+        }
+        let mut stream = store.get(MANIFEST_KEY).await?;
+        let mut buf = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            buf.extend_from_slice(&chunk?);
+        }
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    /// Persist the manifest back to `store`.
+    pub async fn save(&self, store: &dyn Store) -> Result<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        store
+            .put(
+                MANIFEST_KEY,
+                stream::once(async move { Ok(Bytes::from(data)) }).boxed(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Entry> {
+        self.entries.get(url)
+    }
+
+    pub fn set(&mut self, url: String, entry: Entry) {
+        self.entries.insert(url, entry);
+    }
+}
@@ -0,0 +1,224 @@
+//! Pluggable storage backend for the raw IPEDS data.
+//!
+//! The pipeline extracts a large amount of supporting documentation (PDFs,
+//! spreadsheets, HTML, SQL schema dumps) alongside the Access databases it
+//! actually imports. Historically those writes went straight to the local
+//! `out/` directory; the [`Store`] trait abstracts that so the same pipeline
+//! can persist to object storage (S3) when running in an ephemeral container.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use object_store::{aws::AmazonS3Builder, path::Path as ObjPath, ObjectStore};
+use reqwest::Url;
+use tokio::{
+    fs::{create_dir_all, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tokio_util::io::ReaderStream;
+
+/// A byte stream as produced by a download or a zip entry.
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// A destination for the raw IPEDS archive contents.
+///
+/// Paths are always forward-slash separated and relative to the backend root
+/// (the `file://` directory or the `s3://` prefix), so the same keys work
+/// regardless of which implementation is selected.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Write `stream` to `path`, returning the number of bytes stored.
+    async fn put(&self, path: &str, stream: ByteStream) -> Result<u64>;
+
+    /// Read `path` back as a byte stream.
+    async fn get(&self, path: &str) -> Result<ByteStream>;
+
+    /// Whether `path` already exists in the backend.
+    async fn exists(&self, path: &str) -> Result<bool>;
+
+    /// List every key under `prefix`.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// Build a [`Store`] from a `--store` URI: `file://out/` or `s3://bucket/prefix`.
+pub fn from_uri(uri: &str) -> Result<Box<dyn Store>> {
+    let url = Url::parse(uri).map_err(|e| anyhow!("'{}': invalid store URI: {}", uri, e))?;
+    match url.scheme() {
+        "file" => {
+            // `file://out/` (the non-standard relative spelling) parses the
+            // directory as the host and names a path relative to CWD, whereas
+            // `file:///var/data` has no host and names an absolute path whose
+            // leading slash must survive.
+            let root = match url.host_str() {
+                Some(host) => {
+                    let mut root = PathBuf::from(host);
+                    root.push(url.path().trim_start_matches('/'));
+                    root
+                }
+                None => url
+                    .to_file_path()
+                    .map_err(|_| anyhow!("'{}': invalid file store path", uri))?,
+            };
+            Ok(Box::new(LocalStore::new(root)))
+        }
+        "s3" => {
+            let bucket = url
+                .host_str()
+                .ok_or(anyhow!("'{}': s3 URI is missing a bucket", uri))?;
+            let prefix = url.path().trim_start_matches('/').to_owned();
+            Ok(Box::new(ObjectStoreBackend::s3(bucket, prefix)?))
+        }
+        scheme => Err(anyhow!("'{}': unsupported store scheme '{}'", uri, scheme)),
+    }
+}
+
+/// Store backed by a directory on the local filesystem.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+}
+
+#[async_trait]
+impl Store for LocalStore {
+    async fn put(&self, path: &str, mut stream: ByteStream) -> Result<u64> {
+        let filepath = self.resolve(path);
+        if let Some(parent) = filepath.parent() {
+            if !parent.exists() {
+                create_dir_all(parent).await?;
+            }
+        }
+        let f = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&filepath)
+            .await?;
+        let mut wrtr = tokio::io::BufWriter::new(f);
+        let mut written = 0u64;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            wrtr.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        wrtr.flush().await?;
+        Ok(written)
+    }
+
+    async fn get(&self, path: &str) -> Result<ByteStream> {
+        let f = OpenOptions::new().read(true).open(self.resolve(path)).await?;
+        Ok(ReaderStream::new(f).boxed())
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        Ok(self.resolve(path).exists())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut out = Vec::new();
+        let base = self.resolve(prefix);
+        let mut stack = vec![base];
+        while let Some(dir) = stack.pop() {
+            let mut rd = match tokio::fs::read_dir(&dir).await {
+                Ok(rd) => rd,
+                Err(_) => continue,
+            };
+            while let Some(entry) = rd.next_entry().await? {
+                let p = entry.path();
+                if p.is_dir() {
+                    stack.push(p);
+                } else if let Ok(rel) = p.strip_prefix(&self.root) {
+                    out.push(rel.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Store backed by the `object_store` crate (S3).
+pub struct ObjectStoreBackend {
+    inner: Box<dyn ObjectStore>,
+    prefix: String,
+}
+
+impl ObjectStoreBackend {
+    /// Build an S3-backed store, reading credentials/region from the
+    /// environment (`AWS_*`) as `object_store` does by default.
+    pub fn s3(bucket: &str, prefix: String) -> Result<Self> {
+        let s3 = AmazonS3Builder::from_env().with_bucket_name(bucket).build()?;
+        Ok(Self {
+            inner: Box::new(s3),
+            prefix,
+        })
+    }
+
+    fn resolve(&self, path: &str) -> ObjPath {
+        if self.prefix.is_empty() {
+            ObjPath::from(path)
+        } else {
+            ObjPath::from(format!("{}/{}", self.prefix.trim_end_matches('/'), path))
+        }
+    }
+}
+
+#[async_trait]
+impl Store for ObjectStoreBackend {
+    async fn put(&self, path: &str, mut stream: ByteStream) -> Result<u64> {
+        // `object_store` does not expose a streaming multipart writer on every
+        // backend, so buffer the object before uploading. The documentation
+        // files are small; the Access databases never go through a store.
+        let mut buf = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            buf.extend_from_slice(&chunk?);
+        }
+        let len = buf.len() as u64;
+        self.inner.put(&self.resolve(path), buf.into()).await?;
+        Ok(len)
+    }
+
+    async fn get(&self, path: &str) -> Result<ByteStream> {
+        let res = self.inner.get(&self.resolve(path)).await?;
+        Ok(res
+            .into_stream()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+            .boxed())
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        match self.inner.head(&self.resolve(path)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let base = self.resolve(prefix);
+        let strip = format!("{}/", self.prefix.trim_end_matches('/'));
+        let objects: Vec<_> = self.inner.list(Some(&base)).try_collect().await?;
+        Ok(objects
+            .into_iter()
+            .map(|meta| {
+                let key = meta.location.to_string();
+                key.strip_prefix(&strip).map(str::to_owned).unwrap_or(key)
+            })
+            .collect())
+    }
+}
+
+/// Convert a filesystem-relative path into a store key (forward slashes).
+pub fn key(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}